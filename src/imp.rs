@@ -4,8 +4,13 @@ use std::{
     collections::HashMap,
     hash::BuildHasherDefault,
     os::raw::c_int,
-    sync::atomic::{AtomicBool, AtomicUsize, Ordering::Relaxed},
-    sync::Arc,
+    ptr,
+    sync::atomic::{
+        AtomicBool, AtomicPtr, AtomicUsize,
+        Ordering::{Acquire, Relaxed, Release},
+    },
+    sync::{Arc, Mutex, RwLock},
+    thread::{self, ThreadId},
 };
 
 use dashmap::DashMap;
@@ -38,8 +43,93 @@ fn global_store() -> &'static GlobalStore {
     })
 }
 
+/// Hands out small, dense thread indices, recycling them as threads exit.
+///
+/// Modeled on the `thread_local` crate's `thread_id` allocator: each thread is
+/// given the smallest currently-free index, and that index is returned to the
+/// free-list when the thread exits. As a result a `Store`'s shard table only
+/// has to grow to the high-water mark of *concurrently live* threads rather
+/// than to the number of threads ever spawned. The allocator also remembers the
+/// [`ThreadId`] currently owning each index so that [`Store::read_by_thread`]
+/// attributes counts to the *live* owner rather than to whichever thread first
+/// used a now-recycled index.
+struct ThreadIndexAllocator {
+    free_list: Vec<usize>,
+    next: usize,
+    owners: Vec<Option<ThreadId>>,
+}
+
+impl ThreadIndexAllocator {
+    const fn new() -> ThreadIndexAllocator {
+        ThreadIndexAllocator { free_list: Vec::new(), next: 0, owners: Vec::new() }
+    }
+
+    /// Return the smallest currently-free index, minting a fresh one only when
+    /// none can be reused, and record `id` as its current owner.
+    fn alloc(&mut self, id: ThreadId) -> usize {
+        let index = match self.free_list.iter().copied().enumerate().min_by_key(|&(_, i)| i) {
+            Some((pos, index)) => {
+                self.free_list.swap_remove(pos);
+                index
+            }
+            None => {
+                let index = self.next;
+                self.next += 1;
+                index
+            }
+        };
+        if self.owners.len() <= index {
+            self.owners.resize(index + 1, None);
+        }
+        self.owners[index] = Some(id);
+        index
+    }
+
+    fn free(&mut self, index: usize) {
+        self.owners[index] = None;
+        self.free_list.push(index);
+    }
+
+    fn owner(&self, index: usize) -> Option<ThreadId> {
+        self.owners.get(index).copied().flatten()
+    }
+}
+
+static THREAD_INDICES: Mutex<ThreadIndexAllocator> = Mutex::new(ThreadIndexAllocator::new());
+
+fn thread_owner(index: usize) -> Option<ThreadId> {
+    THREAD_INDICES.lock().unwrap_or_else(|poison| poison.into_inner()).owner(index)
+}
+
+/// Per-thread state: the thread's shard index plus a cache of the stores it has
+/// touched.
+///
+/// On thread exit the `Drop` impl folds this thread's shard of every store it
+/// used into that store's retired accumulator and resets it, then returns the
+/// index to the free-list. The owning thread is the only one that ever releases
+/// its index, and only after all of its own `Count` values have been dropped,
+/// so a recycled index is never handed to a new thread while a shard is still
+/// live through the old one.
+struct ThreadState {
+    index: usize,
+    stores: RefCell<HashMap<TypeId, Arc<Store>, BuildHasherDefault<FxHasher>>>,
+}
+
+impl Drop for ThreadState {
+    fn drop(&mut self) {
+        for store in self.stores.borrow().values() {
+            store.retire(self.index);
+        }
+        THREAD_INDICES.lock().unwrap_or_else(|poison| poison.into_inner()).free(self.index);
+    }
+}
+
 thread_local! {
-    static LOCAL: RefCell<HashMap<TypeId, Arc<Store>, BuildHasherDefault<FxHasher>>> = RefCell::default();
+    static THREAD: ThreadState = {
+        let id = thread::current().id();
+        let index = THREAD_INDICES.lock().unwrap_or_else(|poison| poison.into_inner()).alloc(id);
+        ThreadState { index, stores: RefCell::default() }
+    };
 }
 
 pub(crate) fn enable(yes: bool) {
@@ -59,10 +149,12 @@ pub(crate) fn dec<T: 'static>() {
 }
 #[inline(never)]
 fn do_dec(key: TypeId) {
-    LOCAL.with(|local| {
+    THREAD.with(|state| {
+        let shard = state.index;
+        let local = &state.stores;
         // Fast path: we have needed store in thread local map
         if let Some(store) = local.borrow().get(&key) {
-            store.dec();
+            store.dec(shard);
             return;
         }
 
@@ -73,7 +165,7 @@ fn do_dec(key: TypeId) {
         if let Some(store) = global.get(&key) {
             let store = store.value();
             local.borrow_mut().insert(key, Arc::clone(store));
-            store.inc();
+            store.dec(shard);
             return;
         }
 
@@ -89,10 +181,12 @@ pub(crate) fn inc<T: 'static>() {
 }
 #[inline(never)]
 fn do_inc(key: TypeId, name: &'static str) {
-    LOCAL.with(|local| {
+    THREAD.with(|state| {
+        let shard = state.index;
+        let local = &state.stores;
         // Fast path: we have needed store in thread local map
         if let Some(store) = local.borrow().get(&key) {
-            store.inc();
+            store.inc(shard);
             return;
         }
 
@@ -103,7 +197,7 @@ fn do_inc(key: TypeId, name: &'static str) {
             // but some other thread has already initialized the needed store in the global map
             Some(store) => {
                 let store = store.value();
-                store.inc();
+                store.inc(shard);
                 Arc::clone(store)
             }
             // Slow path: we are the first to initialize both global and local maps
@@ -114,7 +208,7 @@ fn do_inc(key: TypeId, name: &'static str) {
                     .downgrade();
                 let store = store.value();
 
-                store.inc();
+                store.inc(shard);
                 Arc::clone(store)
             }
         };
@@ -139,34 +233,236 @@ pub(crate) fn get_all() -> AllCounts {
         })
         .collect::<Vec<_>>();
     entries.sort_by_key(|(name, _counts)| *name);
-    AllCounts { entries }
+    AllCounts { entries, ..AllCounts::default() }
+}
+
+pub(crate) fn get_by_thread<T: 'static>() -> Vec<(ThreadId, Counts)> {
+    do_get_by_thread(TypeId::of::<T>())
+}
+fn do_get_by_thread(key: TypeId) -> Vec<(ThreadId, Counts)> {
+    global_store().entry(key).or_default().value().read_by_thread()
+}
+
+pub(crate) fn get_all_by_thread() -> AllCounts {
+    let mut stores = global_store()
+        .iter()
+        .map(|entry| {
+            let store = entry.value();
+            (store.type_name(), store.read(), store.read_by_thread())
+        })
+        .collect::<Vec<_>>();
+    stores.sort_by_key(|(name, _counts, _by_thread)| *name);
+
+    let mut entries = Vec::with_capacity(stores.len());
+    let mut by_thread = Vec::with_capacity(stores.len());
+    for (name, counts, mut threads) in stores {
+        entries.push((name, counts));
+        threads.sort_by_key(|(thread, _counts)| format!("{:?}", thread));
+        let threads =
+            threads.into_iter().map(|(thread, counts)| (format!("{:?}", thread), counts)).collect();
+        by_thread.push((name, threads));
+    }
+    AllCounts { entries, by_thread }
 }
 
+/// Per-thread counters, each living on its own cache line.
+///
+/// A `Store` owns one `ShardCounts` per active thread index, so the hot path
+/// only ever performs an uncontended `fetch_add` on the current thread's line.
 #[derive(Default)]
-struct Store {
+#[repr(align(128))]
+struct ShardCounts {
     total: AtomicUsize,
     max_live: AtomicUsize,
     live: AtomicUsize,
+}
+
+impl ShardCounts {
+    fn read(&self) -> Counts {
+        Counts {
+            total: self.total.load(Relaxed),
+            max_live: self.max_live.load(Relaxed),
+            live: self.live.load(Relaxed),
+        }
+    }
+
+    /// Move this shard's counts out, leaving it zeroed for a new owner.
+    fn take(&self) -> Counts {
+        Counts {
+            total: self.total.swap(0, Relaxed),
+            max_live: self.max_live.swap(0, Relaxed),
+            live: self.live.swap(0, Relaxed),
+        }
+    }
+}
+
+/// Number of lazily-allocated buckets. Bucket `b` holds `1 << b` shards, so the
+/// buckets together address every thread index below `usize::MAX`.
+const BUCKETS: usize = usize::BITS as usize;
+
+/// Map a thread index to its `(bucket, offset)` within the bucketed shard table.
+///
+/// Bucket `b` starts at index `(1 << b) - 1` and holds `1 << b` shards, so the
+/// buckets never move a shard once allocated.
+#[inline]
+fn bucket_indices(index: usize) -> (usize, usize) {
+    let pos = index + 1;
+    let bucket = (usize::BITS - 1 - pos.leading_zeros()) as usize;
+    let offset = pos - (1 << bucket);
+    (bucket, offset)
+}
+
+struct Store {
+    /// Append-only table of per-thread shards. Buckets are allocated on demand
+    /// and never moved or freed, so a shard keeps a stable address and the hot
+    /// path can reach it through a plain atomic *load* — no shared lock, no RMW
+    /// on any cross-thread state.
+    buckets: [AtomicPtr<ShardCounts>; BUCKETS],
+    /// Counts folded out of shards whose owning threads have exited. Keeping
+    /// them here lets totals stay exact while a thread index (and its shard) is
+    /// recycled by the next thread.
+    retired: ShardCounts,
+    /// Serializes bucket allocation only; never taken on the counting path.
+    grow: Mutex<()>,
+    /// Makes the shard→`retired` transfer in `retire` atomic with respect to
+    /// readers. Writers (retiring threads) take it exclusively; readers take it
+    /// shared. The hot `inc`/`dec` path never touches it.
+    snapshot: RwLock<()>,
     name: &'static str,
 }
 
+impl Default for Store {
+    fn default() -> Store {
+        const NULL: AtomicPtr<ShardCounts> = AtomicPtr::new(ptr::null_mut());
+        Store {
+            buckets: [NULL; BUCKETS],
+            retired: ShardCounts::default(),
+            grow: Mutex::new(()),
+            snapshot: RwLock::new(()),
+            name: "",
+        }
+    }
+}
+
 impl Store {
-    fn inc(&self) {
-        self.total.fetch_add(1, Relaxed);
-        let live = self.live.fetch_add(1, Relaxed) + 1;
-        self.max_live.fetch_max(live, Relaxed);
+    /// Return the current thread's shard, allocating its bucket on first use.
+    #[inline]
+    fn shard(&self, index: usize) -> &ShardCounts {
+        let (bucket, offset) = bucket_indices(index);
+        let mut ptr = self.buckets[bucket].load(Acquire);
+        if ptr.is_null() {
+            ptr = self.alloc_bucket(bucket);
+        }
+        // SAFETY: `bucket` points at `1 << bucket` initialized shards and
+        // `offset < 1 << bucket`; buckets are never freed, so the shard outlives
+        // every reference handed out here.
+        unsafe { &*ptr.add(offset) }
     }
 
-    fn dec(&self) {
-        self.live.fetch_sub(1, Relaxed);
+    #[cold]
+    fn alloc_bucket(&self, bucket: usize) -> *mut ShardCounts {
+        // Recover from a poisoned lock: a panic mid-allocation leaves the table
+        // consistent (the slot is still null), so there is nothing to protect.
+        let _guard = self.grow.lock().unwrap_or_else(|poison| poison.into_inner());
+        let existing = self.buckets[bucket].load(Acquire);
+        if !existing.is_null() {
+            return existing;
+        }
+        let cap = 1usize << bucket;
+        let mut shards = Vec::with_capacity(cap);
+        shards.resize_with(cap, ShardCounts::default);
+        // Leak the bucket: its shards must stay at a stable address for as long
+        // as any thread might reference them, i.e. the rest of the program.
+        let ptr = shards.as_mut_ptr();
+        std::mem::forget(shards);
+        self.buckets[bucket].store(ptr, Release);
+        ptr
     }
 
-    fn read(&self) -> Counts {
-        Counts {
-            total: self.total.load(Relaxed),
-            max_live: self.max_live.load(Relaxed),
-            live: self.live.load(Relaxed),
+    /// Visit every allocated shard together with the thread index it serves.
+    fn for_each_shard(&self, mut f: impl FnMut(usize, &ShardCounts)) {
+        for (bucket, slot) in self.buckets.iter().enumerate() {
+            let ptr = slot.load(Acquire);
+            if ptr.is_null() {
+                continue;
+            }
+            let base = (1usize << bucket) - 1;
+            for offset in 0..(1usize << bucket) {
+                // SAFETY: see `shard`; the bucket holds `1 << bucket` shards.
+                f(base + offset, unsafe { &*ptr.add(offset) });
+            }
+        }
+    }
+
+    fn inc(&self, index: usize) {
+        let shard = self.shard(index);
+        shard.total.fetch_add(1, Relaxed);
+        let live = shard.live.fetch_add(1, Relaxed).wrapping_add(1);
+        // After cross-thread drops a shard's `live` may sit below zero (wrapped);
+        // only a positive value is a peak.
+        if (live as isize) > 0 {
+            shard.max_live.fetch_max(live, Relaxed);
+        }
+    }
+
+    fn dec(&self, index: usize) {
+        let shard = self.shard(index);
+        // `live` is summed across shards, so a cross-thread drop underflowing a
+        // single shard still wraps back to the correct global figure.
+        shard.live.fetch_sub(1, Relaxed);
+    }
+
+    /// Fold the exiting thread's shard into `retired` and zero it so the next
+    /// owner of this index starts clean while the global totals stay exact.
+    fn retire(&self, index: usize) {
+        let (bucket, offset) = bucket_indices(index);
+        let ptr = self.buckets[bucket].load(Acquire);
+        if ptr.is_null() {
+            return;
         }
+        // SAFETY: see `shard`; the bucket holds `1 << bucket` shards.
+        let shard = unsafe { &*ptr.add(offset) };
+        // Hold the snapshot lock exclusively so no reader observes the shard
+        // zeroed before `retired` has absorbed its counts.
+        let _guard = self.snapshot.write().unwrap_or_else(|poison| poison.into_inner());
+        let c = shard.take();
+        self.retired.total.fetch_add(c.total, Relaxed);
+        self.retired.max_live.fetch_add(c.max_live, Relaxed);
+        self.retired.live.fetch_add(c.live, Relaxed);
+    }
+
+    fn read(&self) -> Counts {
+        let _guard = self.snapshot.read().unwrap_or_else(|poison| poison.into_inner());
+        let mut counts = Counts::default();
+        let mut add = |c: Counts| {
+            counts.total = counts.total.wrapping_add(c.total);
+            counts.live = counts.live.wrapping_add(c.live);
+            // Per-shard `max_live` are thread-local peaks; their sum is an upper
+            // bound on the true global maximum of concurrently live instances.
+            counts.max_live = counts.max_live.wrapping_add(c.max_live);
+        };
+        // Counts retired by exited threads plus those of every live shard.
+        add(self.retired.read());
+        self.for_each_shard(|_index, shard| add(shard.read()));
+        counts
+    }
+
+    /// The counts contributed by each thread that is *currently* using this
+    /// store, attributed to its live owner. Counts left behind by exited threads
+    /// live in `retired` and are not attributed to any thread.
+    fn read_by_thread(&self) -> Vec<(ThreadId, Counts)> {
+        let _guard = self.snapshot.read().unwrap_or_else(|poison| poison.into_inner());
+        let mut entries = Vec::new();
+        self.for_each_shard(|index, shard| {
+            let counts = shard.read();
+            if counts.total == 0 && counts.live == 0 {
+                return;
+            }
+            if let Some(thread) = thread_owner(index) {
+                entries.push((thread, counts));
+            }
+        });
+        entries
     }
 
     fn type_name(&self) -> &'static str {
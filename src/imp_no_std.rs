@@ -0,0 +1,162 @@
+//! `no_std` counting backend.
+//!
+//! Instead of a `DashMap<TypeId, Arc<Store>>` global registry (which needs
+//! `std`, `HashMap` and heap-allocated reference counting), every counted type
+//! owns a single `'static` [`Store`] node. The first time a type is counted its
+//! node is pushed onto a lock-free [Treiber stack] whose head is an
+//! `AtomicPtr<Store>`, using the same static lock-free technique as
+//! `thingbuf`'s `StaticThingBuf`. The hot path is then a few atomic
+//! operations on a statically-known address, with no map lookup at all.
+//!
+//! [Treiber stack]: https://en.wikipedia.org/wiki/Treiber_stack
+
+use alloc::vec::Vec;
+use core::{
+    ptr,
+    sync::atomic::{
+        AtomicBool, AtomicPtr, AtomicUsize,
+        Ordering::{Acquire, Relaxed, Release},
+    },
+};
+
+use crate::{AllCounts, Counts};
+
+static ENABLE: AtomicBool = AtomicBool::new(false);
+
+/// Head of the intrusive registry of every [`Store`] that has been counted.
+static REGISTRY: AtomicPtr<Store> = AtomicPtr::new(ptr::null_mut());
+
+/// Associates a counted type with its `'static` [`Store`].
+///
+/// Per-monomorphization statics can't be expressed directly for a generic
+/// `Count<T>`, so each counted type implements this trait to hand back the one
+/// `'static` `Store` that backs it:
+///
+/// ```
+/// # #[cfg(all(feature = "enable", feature = "no_std"))] {
+/// struct Widget;
+/// impl countme::Counted for Widget {
+///     fn store() -> &'static countme::Store {
+///         static STORE: countme::Store = countme::Store::new("Widget");
+///         &STORE
+///     }
+/// }
+/// # }
+/// ```
+pub trait Counted {
+    fn store() -> &'static Store;
+}
+
+/// Placeholder thread identifier for `no_std` builds.
+///
+/// There is no thread registry without `std`, so this type is never
+/// instantiated; it exists only so that [`crate::ThreadCounts`] and the
+/// per-thread query functions have the same shape across both builds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ThreadId(());
+
+/// Per-type counters plus the intrusive link used by the lock-free registry.
+///
+/// Declare one `'static` `Store` per counted type and return it from
+/// [`Counted::store`].
+pub struct Store {
+    total: AtomicUsize,
+    max_live: AtomicUsize,
+    live: AtomicUsize,
+    name: &'static str,
+    /// `true` once this node has been pushed onto the registry.
+    registered: AtomicBool,
+    /// Next node in the Treiber stack, valid only after registration.
+    next: AtomicPtr<Store>,
+}
+
+impl Store {
+    /// Create a new, empty `Store` for a type displayed as `name`.
+    pub const fn new(name: &'static str) -> Store {
+        Store {
+            total: AtomicUsize::new(0),
+            max_live: AtomicUsize::new(0),
+            live: AtomicUsize::new(0),
+            name,
+            registered: AtomicBool::new(false),
+            next: AtomicPtr::new(ptr::null_mut()),
+        }
+    }
+
+    /// Push this node onto the registry exactly once, via a CAS on the head.
+    fn register(&'static self) {
+        if self.registered.swap(true, Relaxed) {
+            return;
+        }
+        let node = self as *const Store as *mut Store;
+        // `Acquire` on the head load and on CAS failure so we synchronize with
+        // every earlier push; `Release` on success so the reader's `Acquire`
+        // traversal sees this node's `next` write.
+        let mut head = REGISTRY.load(Acquire);
+        loop {
+            self.next.store(head, Relaxed);
+            match REGISTRY.compare_exchange_weak(head, node, Release, Acquire) {
+                Ok(_) => break,
+                Err(observed) => head = observed,
+            }
+        }
+    }
+
+    fn inc(&'static self) {
+        self.register();
+        self.total.fetch_add(1, Relaxed);
+        let live = self.live.fetch_add(1, Relaxed) + 1;
+        self.max_live.fetch_max(live, Relaxed);
+    }
+
+    fn dec(&'static self) {
+        self.live.fetch_sub(1, Relaxed);
+    }
+
+    fn read(&self) -> Counts {
+        Counts {
+            total: self.total.load(Relaxed),
+            max_live: self.max_live.load(Relaxed),
+            live: self.live.load(Relaxed),
+        }
+    }
+}
+
+pub(crate) fn enable(yes: bool) {
+    ENABLE.store(yes, Relaxed);
+}
+
+#[inline]
+fn enabled() -> bool {
+    ENABLE.load(Relaxed)
+}
+
+#[inline]
+pub(crate) fn inc<T: Counted>() {
+    if enabled() {
+        T::store().inc()
+    }
+}
+
+#[inline]
+pub(crate) fn dec<T: Counted>() {
+    if enabled() {
+        T::store().dec()
+    }
+}
+
+pub(crate) fn get<T: Counted>() -> Counts {
+    T::store().read()
+}
+
+pub(crate) fn get_all() -> AllCounts {
+    let mut entries = Vec::new();
+    let mut node = REGISTRY.load(Acquire);
+    while let Some(store) = unsafe { node.as_ref() } {
+        entries.push((store.name, store.read()));
+        // `Acquire` so each hop synchronizes with the push that linked it in.
+        node = store.next.load(Acquire);
+    }
+    entries.sort_by_key(|(name, _counts)| *name);
+    AllCounts { entries, ..AllCounts::default() }
+}
@@ -49,9 +49,36 @@
 //! before the program exits (it also enables counting at runtime). Use it only
 //! when you can't modify the main to print counts -- `atexit` is not guaranteed
 //! to work with rust's runtime.
-#[cfg(feature = "enable")]
+//!
+//! The `no_std` Cargo feature swaps the `std`-based global registry for a
+//! lock-free intrusive one so the crate can be used in embedded/kernel
+//! contexts. In that mode each counted type must implement [`Counted`] to hand
+//! back its own `'static` [`Store`]; see [`Counted`] for an example.
+#![cfg_attr(feature = "no_std", no_std)]
+
+#[cfg(feature = "no_std")]
+extern crate alloc;
+
+#[cfg(all(feature = "enable", not(feature = "no_std")))]
 mod imp;
+// The `no_std` backend also carries the `Counted`/`Store` public types, which
+// must exist even when counting is compiled out, so it is gated on `no_std`
+// alone rather than on `enable`.
+#[cfg(feature = "no_std")]
+#[path = "imp_no_std.rs"]
+mod imp;
+
+#[cfg(feature = "no_std")]
+pub use imp::{Counted, Store, ThreadId};
+/// Re-exported so the path `countme::ThreadId` exists in both builds.
+#[cfg(not(feature = "no_std"))]
+pub use std::thread::ThreadId;
 
+#[cfg(feature = "no_std")]
+use alloc::{format, string::String, string::ToString, vec::Vec};
+#[cfg(feature = "no_std")]
+use core::{fmt, marker::PhantomData};
+#[cfg(not(feature = "no_std"))]
 use std::{fmt, marker::PhantomData};
 
 #[derive(Debug, Clone, PartialEq, Eq, Default)]
@@ -60,17 +87,35 @@ pub struct Counts {
     /// The total number of tokens created.
     pub total: usize,
     /// The historical maximum of the `live` count.
+    ///
+    /// In the default `std` build counters are sharded per thread, so this is
+    /// the sum of each thread's local peak and is therefore an upper bound on
+    /// the true global maximum of concurrently live instances. Under `no_std`
+    /// it is the exact global maximum.
     pub max_live: usize,
     /// The number of tokens which were created, but are not destroyed yet.
     pub live: usize,
 }
 
 /// Store this inside your struct as `_c: countme::Count<Self>`.
+///
+/// In `no_std` mode the counted type must implement [`Counted`], so `Count<T>`
+/// carries a `T: Counted` bound; in the default `std` build it is unbounded and
+/// the type is identified by its [`TypeId`](std::any::TypeId).
+#[cfg(not(feature = "no_std"))]
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Count<T> {
     ghost: PhantomData<fn(T)>,
 }
 
+/// Store this inside your struct as `_c: countme::Count<Self>`.
+#[cfg(feature = "no_std")]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Count<T: Counted> {
+    ghost: PhantomData<fn(T)>,
+}
+
+#[cfg(not(feature = "no_std"))]
 impl<T> Default for Count<T> {
     #[inline]
     fn default() -> Self {
@@ -78,6 +123,7 @@ impl<T> Default for Count<T> {
     }
 }
 
+#[cfg(not(feature = "no_std"))]
 impl<T> Clone for Count<T> {
     #[inline]
     fn clone(&self) -> Self {
@@ -85,6 +131,7 @@ impl<T> Clone for Count<T> {
     }
 }
 
+#[cfg(not(feature = "no_std"))]
 impl<T> Count<T> {
     /// Create new `Count`, incrementing the corresponding count.
     #[inline]
@@ -95,6 +142,7 @@ impl<T> Count<T> {
     }
 }
 
+#[cfg(not(feature = "no_std"))]
 impl<T> Drop for Count<T> {
     #[inline]
     fn drop(&mut self) {
@@ -103,6 +151,42 @@ impl<T> Drop for Count<T> {
     }
 }
 
+#[cfg(feature = "no_std")]
+impl<T: Counted> Default for Count<T> {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "no_std")]
+impl<T: Counted> Clone for Count<T> {
+    #[inline]
+    fn clone(&self) -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "no_std")]
+impl<T: Counted> Count<T> {
+    /// Create new `Count`, incrementing the corresponding count.
+    #[inline]
+    pub fn new() -> Count<T> {
+        #[cfg(feature = "enable")]
+        imp::inc::<T>();
+        Count { ghost: PhantomData }
+    }
+}
+
+#[cfg(feature = "no_std")]
+impl<T: Counted> Drop for Count<T> {
+    #[inline]
+    fn drop(&mut self) {
+        #[cfg(feature = "enable")]
+        imp::dec::<T>();
+    }
+}
+
 /// Enable or disable counting at runtime.
 ///
 /// Counting is enabled by default.
@@ -112,6 +196,7 @@ pub fn enable(_yes: bool) {
 }
 
 /// Returns the counts for the `T` type.
+#[cfg(not(feature = "no_std"))]
 #[inline]
 pub fn get<T>() -> Counts {
     #[cfg(feature = "enable")]
@@ -124,6 +209,20 @@ pub fn get<T>() -> Counts {
     }
 }
 
+/// Returns the counts for the `T` type.
+#[cfg(feature = "no_std")]
+#[inline]
+pub fn get<T: Counted>() -> Counts {
+    #[cfg(feature = "enable")]
+    {
+        return imp::get::<T>();
+    }
+    #[cfg(not(feature = "enable"))]
+    {
+        return Counts::default();
+    }
+}
+
 /// Returns a collection of counts for all types.
 pub fn get_all() -> AllCounts {
     #[cfg(feature = "enable")]
@@ -136,10 +235,61 @@ pub fn get_all() -> AllCounts {
     }
 }
 
+/// The counts contributed by a single thread.
+#[derive(Debug, Clone)]
+pub struct ThreadCounts {
+    /// The thread these counts belong to.
+    pub thread: ThreadId,
+    /// The counts this thread contributed.
+    pub counts: Counts,
+}
+
+/// Returns the per-thread breakdown of the counts for the `T` type.
+///
+/// Each entry is the slice of counts owned by one thread, which is useful for
+/// pinpointing *which* thread is responsible for a leak or an allocation spike.
+/// Returns an empty vector when counting is disabled, and always under
+/// `no_std`, which has no thread registry.
+pub fn get_by_thread<T>() -> Vec<ThreadCounts> {
+    #[cfg(all(feature = "enable", not(feature = "no_std")))]
+    {
+        return imp::get_by_thread::<T>()
+            .into_iter()
+            .map(|(thread, counts)| ThreadCounts { thread, counts })
+            .collect();
+    }
+    #[cfg(not(all(feature = "enable", not(feature = "no_std"))))]
+    {
+        return Vec::new();
+    }
+}
+
+/// Returns the counts for all types, with a per-thread breakdown attached.
+///
+/// The result displays like [`get_all`], except its alternate form (`{:#}`)
+/// expands each type into one row per contributing thread. Returns empty counts
+/// when counting is disabled or under `no_std`.
+pub fn get_all_by_thread() -> AllCounts {
+    #[cfg(all(feature = "enable", not(feature = "no_std")))]
+    {
+        return imp::get_all_by_thread();
+    }
+    #[cfg(not(all(feature = "enable", not(feature = "no_std"))))]
+    {
+        return AllCounts::default();
+    }
+}
+
 /// A collection of counts for all types.
+///
+/// When built by [`get_all_by_thread`], the alternate `Display` form (`{:#}`)
+/// expands each type into one row per contributing thread.
 #[derive(Default, Clone, Debug)]
 pub struct AllCounts {
     entries: Vec<(&'static str, Counts)>,
+    /// Per-thread breakdown, keyed by the same type name as `entries`. The
+    /// thread is stored pre-formatted so this type stays `no_std`-friendly.
+    by_thread: Vec<(&'static str, Vec<(String, Counts)>)>,
 }
 
 impl fmt::Display for AllCounts {
@@ -162,27 +312,52 @@ impl fmt::Display for AllCounts {
                 writeln!(f, "counts are disabled")
             };
         }
-        let max_width =
-            self.entries.iter().map(|(name, _count)| name.chars().count()).max().unwrap_or(0);
+        let name_width = self
+            .entries
+            .iter()
+            .map(|(name, _count)| name.chars().count())
+            .chain(self.by_thread.iter().flat_map(|(_name, threads)| {
+                // Indented thread rows are two spaces wider than their type name.
+                threads.iter().map(|(thread, _counts)| thread.chars().count() + 2)
+            }))
+            .max()
+            .unwrap_or(0);
         for (name, counts) in &self.entries {
             writeln!(
                 f,
-                "{:<max_width$}  {:>12} {:>12} {:>12}",
+                "{:<name_width$}  {:>12} {:>12} {:>12}",
                 name,
                 sep(counts.total),
                 sep(counts.max_live),
                 sep(counts.live),
-                max_width = max_width
+                name_width = name_width
             )?;
+            if f.alternate() {
+                if let Some((_name, threads)) =
+                    self.by_thread.iter().find(|(other, _threads)| other == name)
+                {
+                    for (thread, counts) in threads {
+                        writeln!(
+                            f,
+                            "  {:<inner_width$}  {:>12} {:>12} {:>12}",
+                            thread,
+                            sep(counts.total),
+                            sep(counts.max_live),
+                            sep(counts.live),
+                            inner_width = name_width - 2
+                        )?;
+                    }
+                }
+            }
         }
         writeln!(
             f,
-            "{:<max_width$}  {:>12} {:>12} {:>12}",
+            "{:<name_width$}  {:>12} {:>12} {:>12}",
             "",
             "total",
             "max_live",
             "live",
-            max_width = max_width
+            name_width = name_width
         )?;
         Ok(())
     }
@@ -0,0 +1,98 @@
+//! Multi-threaded checks for the sharded counters, index recycling and the
+//! per-thread breakdown. They are no-ops unless the `enable` feature is on.
+
+use std::sync::{Arc, Barrier};
+use std::thread;
+
+use countme::Count;
+
+#[derive(Default)]
+struct Recycled {
+    _c: Count<Self>,
+}
+
+#[derive(Default)]
+struct Tracked {
+    _c: Count<Self>,
+}
+
+/// Spawning and joining threads one wave at a time recycles the same thread
+/// index repeatedly. Totals must stay exact, and once every worker has exited
+/// nothing may remain attributed to a (dead) thread.
+#[test]
+fn recycling_keeps_totals_exact() {
+    if cfg!(not(feature = "enable")) {
+        return;
+    }
+    countme::enable(true);
+
+    const WAVES: usize = 8;
+    const K: usize = 1_000;
+    for _ in 0..WAVES {
+        thread::spawn(|| {
+            let mut xs = Vec::with_capacity(K);
+            for _ in 0..K {
+                xs.push(Recycled::default());
+            }
+            // `xs` is dropped here, before the thread exits.
+        })
+        .join()
+        .unwrap();
+    }
+
+    let counts = countme::get::<Recycled>();
+    assert_eq!(counts.total, WAVES * K);
+    assert_eq!(counts.live, 0);
+
+    // All workers have exited, so their (recycled) indices attribute to no live
+    // thread; their counts live in the retired accumulator instead.
+    #[cfg(not(feature = "no_std"))]
+    assert!(countme::get_by_thread::<Recycled>().is_empty());
+}
+
+/// While several threads are concurrently holding live instances, the
+/// per-thread breakdown must list exactly those threads and their live counts
+/// must sum to the aggregate.
+#[test]
+fn per_thread_breakdown_tracks_live_threads() {
+    if cfg!(not(feature = "enable")) {
+        return;
+    }
+    countme::enable(true);
+
+    const N: usize = 4;
+    const K: usize = 100;
+    let created = Arc::new(Barrier::new(N + 1));
+    let release = Arc::new(Barrier::new(N + 1));
+
+    let mut handles = Vec::new();
+    for _ in 0..N {
+        let created = Arc::clone(&created);
+        let release = Arc::clone(&release);
+        handles.push(thread::spawn(move || {
+            let xs: Vec<Tracked> = (0..K).map(|_| Tracked::default()).collect();
+            created.wait();
+            // Keep `xs` live until the main thread has inspected the counts.
+            release.wait();
+            drop(xs);
+        }));
+    }
+
+    created.wait();
+    assert_eq!(countme::get::<Tracked>().live, N * K);
+
+    #[cfg(not(feature = "no_std"))]
+    {
+        let by_thread = countme::get_by_thread::<Tracked>();
+        let live_threads = by_thread.iter().filter(|tc| tc.counts.live > 0).count();
+        assert_eq!(live_threads, N);
+        let live_sum: usize = by_thread.iter().map(|tc| tc.counts.live).sum();
+        assert_eq!(live_sum, N * K);
+    }
+
+    release.wait();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    assert_eq!(countme::get::<Tracked>().live, 0);
+}